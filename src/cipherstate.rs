@@ -0,0 +1,94 @@
+//! The `CipherState` AEAD wrapper used for both handshake-phase encryption (via
+//! `SymmetricState`) and the transport phase (via `TransportState`).
+
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use constants::CIPHERKEYLEN;
+
+/// The pair of `CipherState`s a finished handshake splits into: index 0 is
+/// initiator-to-responder, index 1 is responder-to-initiator.
+pub type CipherStates = (CipherState, CipherState);
+
+/// Wraps a ChaChaPoly key and the nonce counter used with it. Has no key (and cannot
+/// encrypt/decrypt) until `set_key` is called.
+pub struct CipherState {
+    key: Option<[u8; CIPHERKEYLEN]>,
+    n: u64,
+}
+
+impl CipherState {
+    pub fn new() -> CipherState {
+        CipherState { key: None, n: 0 }
+    }
+
+    pub fn name(&self) -> &'static str {
+        "ChaChaPoly"
+    }
+
+    pub fn has_key(&self) -> bool {
+        self.key.is_some()
+    }
+
+    /// Sets this cipherstate's key, resetting its nonce counter to 0 as required by
+    /// the Noise spec's `InitializeKey(key)`. Callers that need to rekey *without*
+    /// resetting the nonce (e.g. `TransportState::rekey_initiator`) must snapshot and
+    /// restore the nonce around this call themselves.
+    pub fn set_key(&mut self, key: [u8; CIPHERKEYLEN]) {
+        self.key = Some(key);
+        self.n = 0;
+    }
+
+    /// The next nonce that will be used by `encrypt`/`decrypt` if called with no
+    /// explicit override.
+    pub fn nonce(&self) -> u64 {
+        self.n
+    }
+
+    /// Overrides the nonce counter directly, for explicit (stateless-transport) nonce
+    /// management.
+    pub fn set_nonce(&mut self, nonce: u64) {
+        self.n = nonce;
+    }
+
+    fn nonce_bytes(n: u64) -> Nonce {
+        let mut bytes = [0u8; 12];
+        bytes[4..].copy_from_slice(&n.to_le_bytes());
+        *Nonce::from_slice(&bytes)
+    }
+
+    /// Encrypts `plaintext` with `nonce` and associated data `authtext`, writing the
+    /// ciphertext (plus tag) to `out` and returning its length. Advances the nonce
+    /// counter to `nonce + 1`, wrapping at `u64::MAX` -- `rekey`'s
+    /// `REKEY(k) = ENCRYPT(k, maxnonce, zerolen, zeros)` intentionally calls this with
+    /// `nonce == u64::MAX` and relies on the bookkeeping not panicking.
+    pub fn encrypt(&mut self, nonce: u64, authtext: &[u8], plaintext: &[u8], out: &mut [u8]) -> usize {
+        let key = self.key.expect("encrypt called on a cipherstate with no key set");
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+        let sealed = cipher
+            .encrypt(&Self::nonce_bytes(nonce), Payload { msg: plaintext, aad: authtext })
+            .expect("chacha20poly1305 encryption cannot fail for valid inputs");
+        out[..sealed.len()].copy_from_slice(&sealed);
+        self.n = nonce.wrapping_add(1);
+        sealed.len()
+    }
+
+    /// Decrypts `ciphertext` (including its trailing tag) with `nonce` and associated
+    /// data `authtext`, writing the plaintext to `out` and returning its length.
+    /// Advances the nonce counter to `nonce + 1` on success, wrapping at `u64::MAX`.
+    pub fn decrypt(&mut self, nonce: u64, authtext: &[u8], ciphertext: &[u8], out: &mut [u8]) -> Result<usize, ()> {
+        let key = self.key.ok_or(())?;
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+        let opened = cipher
+            .decrypt(&Self::nonce_bytes(nonce), Payload { msg: ciphertext, aad: authtext })
+            .map_err(|_| ())?;
+        out[..opened.len()].copy_from_slice(&opened);
+        self.n = nonce.wrapping_add(1);
+        Ok(opened.len())
+    }
+}
+
+impl Default for CipherState {
+    fn default() -> CipherState {
+        CipherState::new()
+    }
+}
@@ -0,0 +1,14 @@
+//! Fixed sizes shared across the handshake and transport machinery.
+
+/// Largest public key size across supported `Dh` implementations (448 needs 56).
+pub const MAXDHLEN: usize = 56;
+/// Length in bytes of an AEAD authentication tag.
+pub const TAGLEN: usize = 16;
+/// Length in bytes of a pre-shared key.
+pub const PSKLEN: usize = 32;
+/// Largest permitted Noise message, per the spec.
+pub const MAXMSGLEN: usize = 65535;
+/// Largest hash digest size across supported `Hash` implementations (BLAKE2b needs 64).
+pub const MAXHASHLEN: usize = 64;
+/// Length in bytes of a cipher key.
+pub const CIPHERKEYLEN: usize = 32;
@@ -0,0 +1,299 @@
+//! WireGuard-style `mac1`/`mac2` trailers and cookie replies.
+//!
+//! This is an optional, feature-gated subsystem (enable with the `cookies` feature) that lets a responder shed load from a handshake flood without
+//! keeping any per-connection state or doing a single DH operation. It wraps the byte
+//! buffers produced by [`HandshakeState::write_handshake_message`] and consumed by
+//! [`HandshakeState::read_handshake_message`] with two trailing 16-byte MAC fields:
+//!
+//! - `mac1` is always present and lets a responder drop spoofed/malformed messages
+//!   before doing any DH, using only its own static public key.
+//! - `mac2` is zero until the initiator has been handed a cookie (in a *cookie reply*,
+//!   sent by a responder that is currently overloaded); once held, the initiator proves
+//!   it by keying `mac2` with the cookie on subsequent messages.
+//!
+//! [`HandshakeState::write_handshake_message`]: crate::handshakestate::HandshakeState::write_handshake_message
+//! [`HandshakeState::read_handshake_message`]: crate::handshakestate::HandshakeState::read_handshake_message
+
+use blake2::digest::consts::U16;
+use blake2::digest::{Digest, FixedOutput, KeyInit, Update};
+use blake2::{Blake2s256, Blake2sMac};
+use chacha20poly1305::aead::{Aead, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+use Random;
+use error::Error;
+
+const LABEL_MAC1: &[u8] = b"mac1----";
+const LABEL_COOKIE: &[u8] = b"cookie--";
+
+/// Size in bytes of both the `mac1` and `mac2` trailers.
+pub const MAC_LEN: usize = 16;
+/// Size in bytes of a cookie.
+pub const COOKIE_LEN: usize = 16;
+const XNONCE_LEN: usize = 24;
+
+/// How long a responder's `changing_secret` is used before it's rotated, bounding how
+/// long a leaked cookie remains valid.
+const CHANGING_SECRET_LIFETIME: Duration = Duration::from_secs(120);
+
+/// Adds and checks `mac1`/`mac2` trailers, and seals/opens cookie replies, for a
+/// responder identified by `responder_static_pubkey`.
+///
+/// Holding only the responder's static public key (rather than a full `HandshakeState`)
+/// is the point: `mac1` can be computed and checked even before any `HandshakeState`
+/// exists, so a flood of spoofed initiations can be rejected before spending a single DH.
+pub struct CookieState {
+    mac1_key: [u8; 32],
+    cookie_key: [u8; 32],
+    changing_secret: [u8; 32],
+    changing_secret_born: Instant,
+}
+
+impl CookieState {
+    /// Builds the `mac1`/cookie keys for a responder whose static public key is
+    /// `responder_static_pubkey`, and draws an initial `changing_secret` from `rng`.
+    pub fn new(responder_static_pubkey: &[u8], rng: &mut dyn Random) -> CookieState {
+        let mut changing_secret = [0u8; 32];
+        rng.fill_bytes(&mut changing_secret);
+        CookieState {
+            mac1_key: hash_label(LABEL_MAC1, responder_static_pubkey),
+            cookie_key: hash_label(LABEL_COOKIE, responder_static_pubkey),
+            changing_secret,
+            changing_secret_born: Instant::now(),
+        }
+    }
+
+    fn rotate_secret_if_stale(&mut self, rng: &mut dyn Random) {
+        if self.changing_secret_born.elapsed() >= CHANGING_SECRET_LIFETIME {
+            rng.fill_bytes(&mut self.changing_secret);
+            self.changing_secret_born = Instant::now();
+        }
+    }
+
+    /// Appends `mac1` (and `mac2`, keyed by `cookie` if held, else all-zero) to
+    /// `message[..len]`. `message` must have `2 * MAC_LEN` spare bytes past `len`.
+    pub fn seal(&self, message: &mut [u8], len: usize, cookie: Option<&[u8; COOKIE_LEN]>) -> Result<usize, Error> {
+        if len + 2 * MAC_LEN > message.len() {
+            return Err(Error::Input);
+        }
+
+        let mac1 = keyed_mac(&self.mac1_key, &message[..len]);
+        message[len..len + MAC_LEN].copy_from_slice(&mac1);
+
+        let mac2 = match cookie {
+            Some(cookie) => keyed_mac(cookie, &message[..len + MAC_LEN]),
+            None => [0u8; MAC_LEN],
+        };
+        message[len + MAC_LEN..len + 2 * MAC_LEN].copy_from_slice(&mac2);
+
+        Ok(len + 2 * MAC_LEN)
+    }
+
+    /// Validates `mac1` on `message[..len]` (which must include the trailer). A caller
+    /// under load would additionally check `mac2` itself with [`CookieState::verify_mac2`]
+    /// and, if it doesn't match a cookie it issued, reply with
+    /// [`CookieState::seal_cookie_reply`] instead of proceeding to
+    /// `HandshakeState::read_handshake_message`.
+    pub fn verify_mac1(&self, message: &[u8], len: usize) -> Result<(), Error> {
+        if len < 2 * MAC_LEN {
+            return Err(Error::Input);
+        }
+        let mac1_offset = len - 2 * MAC_LEN;
+        let expected = keyed_mac(&self.mac1_key, &message[..mac1_offset]);
+        if constant_time_eq(&expected, &message[mac1_offset..mac1_offset + MAC_LEN]) {
+            Ok(())
+        } else {
+            Err(Error::Decrypt)
+        }
+    }
+
+    /// Validates `mac2` on `message[..len]` (which must include both trailers) against
+    /// the cookie this responder would issue `source_ip`. Only meaningful once a
+    /// responder is under enough load to start demanding cookies -- a message whose
+    /// `mac2` is all-zero (no cookie held yet) is rejected here too, since the caller
+    /// is expected to check this only after deciding it wants proof-of-cookie.
+    pub fn verify_mac2(&mut self, message: &[u8], len: usize, source_ip: IpAddr, rng: &mut dyn Random) -> Result<(), Error> {
+        if len < 2 * MAC_LEN {
+            return Err(Error::Input);
+        }
+        let mac2_offset = len - MAC_LEN;
+        let mac1_offset = mac2_offset - MAC_LEN;
+        let cookie = self.cookie_for(source_ip, rng);
+        let expected = keyed_mac(&cookie, &message[..mac1_offset + MAC_LEN]);
+        if constant_time_eq(&expected, &message[mac2_offset..mac2_offset + MAC_LEN]) {
+            Ok(())
+        } else {
+            Err(Error::Decrypt)
+        }
+    }
+
+    /// Computes the cookie for `source_ip`, rotating `changing_secret` first if it's
+    /// older than its ~2 minute lifetime.
+    pub fn cookie_for(&mut self, source_ip: IpAddr, rng: &mut dyn Random) -> [u8; COOKIE_LEN] {
+        self.rotate_secret_if_stale(rng);
+        let addr_bytes: &[u8] = match &source_ip {
+            IpAddr::V4(v4) => &v4.octets(),
+            IpAddr::V6(v6) => &v6.octets(),
+        };
+        keyed_mac(&self.changing_secret, addr_bytes)
+    }
+
+    /// Seals a cookie reply for `source_ip`, AAD-bound to the `mac1` of the initiation
+    /// it answers so an attacker can't replay it against a different message.
+    pub fn seal_cookie_reply(
+        &mut self,
+        source_ip: IpAddr,
+        received_mac1: &[u8; MAC_LEN],
+        rng: &mut dyn Random,
+        out: &mut [u8],
+    ) -> Result<usize, Error> {
+        let cookie = self.cookie_for(source_ip, rng);
+
+        let mut nonce_bytes = [0u8; XNONCE_LEN];
+        rng.fill_bytes(&mut nonce_bytes);
+
+        let cipher = XChaCha20Poly1305::new(Key::from_slice(&self.cookie_key));
+        let sealed = cipher
+            .encrypt(XNonce::from_slice(&nonce_bytes), Payload { msg: &cookie, aad: received_mac1 })
+            .map_err(|_| Error::Decrypt)?;
+
+        if out.len() < XNONCE_LEN + sealed.len() {
+            return Err(Error::Input);
+        }
+        out[..XNONCE_LEN].copy_from_slice(&nonce_bytes);
+        out[XNONCE_LEN..XNONCE_LEN + sealed.len()].copy_from_slice(&sealed);
+        Ok(XNONCE_LEN + sealed.len())
+    }
+
+    /// Decrypts a cookie reply, yielding the cookie to key `mac2` with on subsequent
+    /// messages to this responder.
+    pub fn open_cookie_reply(&self, reply: &[u8], received_mac1: &[u8; MAC_LEN]) -> Result<[u8; COOKIE_LEN], Error> {
+        if reply.len() < XNONCE_LEN {
+            return Err(Error::Input);
+        }
+        let (nonce_bytes, sealed) = reply.split_at(XNONCE_LEN);
+
+        let cipher = XChaCha20Poly1305::new(Key::from_slice(&self.cookie_key));
+        let cookie = cipher
+            .decrypt(XNonce::from_slice(nonce_bytes), Payload { msg: sealed, aad: received_mac1 })
+            .map_err(|_| Error::Decrypt)?;
+        if cookie.len() != COOKIE_LEN {
+            return Err(Error::Decrypt);
+        }
+
+        let mut out = [0u8; COOKIE_LEN];
+        out.copy_from_slice(&cookie);
+        Ok(out)
+    }
+}
+
+fn hash_label(label: &[u8], pubkey: &[u8]) -> [u8; 32] {
+    let mut hasher = Blake2s256::new();
+    Update::update(&mut hasher, label);
+    Update::update(&mut hasher, pubkey);
+    let digest = hasher.finalize_fixed();
+    let mut out = [0u8; 32];
+    out.copy_from_slice(&digest);
+    out
+}
+
+fn keyed_mac(key: &[u8], data: &[u8]) -> [u8; MAC_LEN] {
+    let mut mac = Blake2sMac::<U16>::new_from_slice(key).expect("mac key is valid length");
+    Update::update(&mut mac, data);
+    let result = mac.finalize_fixed();
+    let mut out = [0u8; MAC_LEN];
+    out.copy_from_slice(&result);
+    out
+}
+
+/// Constant-time comparison so mac1 rejection doesn't leak timing information.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b.iter()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestRng(u8);
+
+    impl Random for TestRng {
+        fn fill_bytes(&mut self, out: &mut [u8]) {
+            for byte in out.iter_mut() {
+                self.0 = self.0.wrapping_add(1).wrapping_mul(37).wrapping_add(11);
+                *byte = self.0;
+            }
+        }
+    }
+
+    fn responder_pubkey() -> [u8; 32] {
+        [7u8; 32]
+    }
+
+    #[test]
+    fn mac1_round_trips_and_rejects_tampering() {
+        let mut rng = TestRng(1);
+        let state = CookieState::new(&responder_pubkey(), &mut rng);
+
+        let mut message = [0u8; 64 + 2 * MAC_LEN];
+        message[..64].copy_from_slice(&[0x42u8; 64]);
+        let len = state.seal(&mut message, 64, None).unwrap();
+
+        state.verify_mac1(&message[..len], len).expect("freshly sealed mac1 must verify");
+
+        message[0] ^= 1;
+        assert!(state.verify_mac1(&message[..len], len).is_err(), "tampered payload must fail mac1");
+    }
+
+    #[test]
+    fn mac2_is_validated_against_the_issued_cookie() {
+        let mut rng = TestRng(2);
+        let mut state = CookieState::new(&responder_pubkey(), &mut rng);
+        let source_ip: IpAddr = "203.0.113.5".parse().unwrap();
+
+        let mut message = [0u8; 64 + 2 * MAC_LEN];
+        message[..64].copy_from_slice(&[0x24u8; 64]);
+        let mac1_only_len = state.seal(&mut message, 64, None).unwrap();
+        let mac1 = {
+            let mut mac1 = [0u8; MAC_LEN];
+            mac1.copy_from_slice(&message[64..64 + MAC_LEN]);
+            mac1
+        };
+
+        let cookie = state.cookie_for(source_ip, &mut rng);
+        let len = state.seal(&mut message, 64, Some(&cookie)).unwrap();
+        assert_eq!(len, mac1_only_len);
+
+        state.verify_mac2(&message[..len], len, source_ip, &mut rng)
+            .expect("mac2 keyed with the cookie this responder would issue must verify");
+
+        let other_ip: IpAddr = "203.0.113.6".parse().unwrap();
+        assert!(
+            state.verify_mac2(&message[..len], len, other_ip, &mut rng).is_err(),
+            "mac2 must not verify against a cookie issued for a different source"
+        );
+
+        let _ = mac1;
+    }
+
+    #[test]
+    fn cookie_reply_round_trips() {
+        let mut rng = TestRng(3);
+        let mut state = CookieState::new(&responder_pubkey(), &mut rng);
+        let source_ip: IpAddr = "198.51.100.1".parse().unwrap();
+        let received_mac1 = [0x55u8; MAC_LEN];
+
+        let mut reply = [0u8; XNONCE_LEN + COOKIE_LEN + 16];
+        let len = state.seal_cookie_reply(source_ip, &received_mac1, &mut rng, &mut reply).unwrap();
+
+        let cookie = state.open_cookie_reply(&reply[..len], &received_mac1).unwrap();
+        assert_eq!(cookie, state.cookie_for(source_ip, &mut rng));
+
+        let wrong_mac1 = [0xaau8; MAC_LEN];
+        assert!(state.open_cookie_reply(&reply[..len], &wrong_mac1).is_err());
+    }
+}
@@ -31,6 +31,11 @@ pub enum Error {
     #[cfg(feature = "hfs")]
     Kem,
 
+    /// The per-source token bucket was empty; the caller should silently drop the
+    /// packet rather than doing any further handshake processing.
+    #[cfg(feature = "cookies")]
+    RateLimited,
+
     /// This enum may grow additional variants, so this makes sure clients
     /// don't count on exhaustive matching. (Otherwise, adding a new variant
     /// could break existing code.)
@@ -110,6 +115,7 @@ pub enum StateProblem {
     HandshakeAlreadyFinished,
     OneWay,
     StatelessTransportMode,
+    ReplayedHandshake,
 }
 
 impl From<StateProblem> for Error {
@@ -129,6 +135,7 @@ impl fmt::Display for Error {
             Error::Dh => write!(f, "diffie-hellman error"),
             Error::Decrypt => write!(f, "decrypt error"),
             #[cfg(feature = "hfs")] Error::Kem => write!(f, "kem error"),
+            #[cfg(feature = "cookies")] Error::RateLimited => write!(f, "rate limited"),
             Error::__Nonexhaustive => write!(f, "Nonexhaustive"),
         }
     }
@@ -23,6 +23,7 @@ pub struct HandshakeState {
     s: Toggle<Box<Dh>>,
     e: Toggle<Box<Dh>>,
     fixed_ephemeral: bool,
+    elligator: bool,
     rs: Toggle<[u8; MAXDHLEN]>,
     re: Toggle<[u8; MAXDHLEN]>,
     initiator: bool,
@@ -30,6 +31,7 @@ pub struct HandshakeState {
     psks: [Option<[u8; PSKLEN]>; 10],
     my_turn: bool,
     message_patterns: MessagePatterns,
+    prologue: Vec<u8>,
 }
 
 impl HandshakeState {
@@ -40,6 +42,7 @@ impl HandshakeState {
         s : Toggle<Box<Dh>>,
         e : Toggle<Box<Dh>>,
         fixed_ephemeral: bool,
+        elligator: bool,
         rs: Toggle<[u8; MAXDHLEN]>,
         re: Toggle<[u8; MAXDHLEN]>,
         initiator: bool,
@@ -55,10 +58,28 @@ impl HandshakeState {
             return Err(PrereqError(format!("key lengths aren't right. my pub: {}, their: {}", s.pub_len(), rs.len())));
         }
 
-        // TODO support modifiers
+        if elligator {
+            // No published curve25519-dalek release exposes a Montgomery Elligator2
+            // implementation (only an internal, pub(crate) hash-to-curve helper exists),
+            // and no `Dh` overrides `elligator2_representative`, so `elligator: true` is
+            // rejected unconditionally, whatever the DH type; `write_handshake_message` and
+            // `read_handshake_message` always put the raw ephemeral public key on the wire.
+            return Err(NoiseError::InputError("elligator2 encoding is not implemented"));
+        }
+
         let mut handshake_name = ArrayString::<[u8; 128]>::from("Noise_").unwrap();
         let tokens = HandshakeTokens::try_from(handshake.clone()).map_err(|e| NoiseError::InputError(e))?;
         handshake_name.push_str(handshake.pattern.as_str()).unwrap();
+        for modifier in &handshake.modifiers.list {
+            handshake_name.push('+').unwrap();
+            match *modifier {
+                HandshakeModifier::Psk(n) => {
+                    handshake_name.push_str("psk").unwrap();
+                    handshake_name.push((b'0' + n) as char).unwrap();
+                },
+                HandshakeModifier::Fallback => handshake_name.push_str("fallback").unwrap(),
+            }
+        }
         handshake_name.push('_').unwrap();
         handshake_name.push_str(s.name()).unwrap();
         handshake_name.push('_').unwrap();
@@ -72,15 +93,30 @@ impl HandshakeState {
         symmetricstate.mix_hash(prologue);
 
         let dh_len = s.pub_len();
-        if initiator {
-            for token in tokens.premsg_pattern_i {
-                match *token {
-                    Token::S => {assert!(s.is_on()); symmetricstate.mix_hash(s.pubkey());},
-                    Token::E => {assert!(e.is_on()); symmetricstate.mix_hash(e.pubkey());},
-                    _ => unreachable!()
-                }
+
+        // `premsg_pattern_i` is only non-empty for XXfallback's `-> e`, which exists to
+        // carry forward an ephemeral that was *received*, not generated: a responder
+        // restarting a failed IK via `into_fallback` becomes this pattern's initiator
+        // without ever having toggled on its own `e`/`s`, holding that key only in
+        // `re`/`rs`. So read from whichever side actually has the key, rather than
+        // hardcoding "initiator means local" the way `premsg_pattern_r` below does --
+        // that branching is safe there because `premsg_pattern_r` is only non-empty for
+        // IK, where the initiator truly does hold the responder's static remotely.
+        for token in &tokens.premsg_pattern_i {
+            match *token {
+                Token::S => {
+                    if s.is_on() { symmetricstate.mix_hash(s.pubkey()); }
+                    else { assert!(rs.is_on()); symmetricstate.mix_hash(&rs[..dh_len]); }
+                },
+                Token::E => {
+                    if e.is_on() { symmetricstate.mix_hash(e.pubkey()); }
+                    else { assert!(re.is_on()); symmetricstate.mix_hash(&re[..dh_len]); }
+                },
+                _ => unreachable!()
             }
-            for token in tokens.premsg_pattern_r {
+        }
+        if initiator {
+            for token in &tokens.premsg_pattern_r {
                 match *token {
                     Token::S => {assert!(rs.is_on()); symmetricstate.mix_hash(&rs[..dh_len]);},
                     Token::E => {assert!(re.is_on()); symmetricstate.mix_hash(&re[..dh_len]);},
@@ -88,14 +124,7 @@ impl HandshakeState {
                 }
             }
         } else {
-            for token in tokens.premsg_pattern_i {
-                match *token {
-                    Token::S => {assert!(rs.is_on()); symmetricstate.mix_hash(&rs[..dh_len]);},
-                    Token::E => {assert!(re.is_on()); symmetricstate.mix_hash(&re[..dh_len]);},
-                    _ => unreachable!()
-                }
-            }
-            for token in tokens.premsg_pattern_r {
+            for token in &tokens.premsg_pattern_r {
                 match *token {
                     Token::S => {assert!(s.is_on()); symmetricstate.mix_hash(s.pubkey());},
                     Token::E => {assert!(e.is_on()); symmetricstate.mix_hash(e.pubkey());},
@@ -111,16 +140,51 @@ impl HandshakeState {
             s: s,
             e: e,
             fixed_ephemeral: fixed_ephemeral,
-            rs: rs, 
+            elligator: elligator,
+            rs: rs,
             re: re,
             initiator: initiator,
             handshake: handshake,
             psks: psks,
             my_turn: initiator,
             message_patterns: tokens.msg_patterns.into(),
+            prologue: prologue.to_vec(),
         })
     }
 
+    /// Reinitializes a failed handshake as a new one, most commonly used to support
+    /// Noise Pipes: a responder that fails to decrypt an IK initiation can restart as
+    /// XXfallback, reusing the peer's already-received ephemeral (and any known static)
+    /// as pre-message material instead of tearing the session down.
+    ///
+    /// `new_handshake` must carry the `fallback` modifier; its premessage pattern is
+    /// re-mixed into a fresh transcript hash exactly as `HandshakeState::new` would for a
+    /// brand new handshake, so both peers end up with matching `handshake_name` hashes.
+    ///
+    /// See: http://noiseprotocol.org/noise.html#noise-pipes
+    pub fn into_fallback(self, new_handshake: HandshakeChoice) -> Result<HandshakeState, NoiseError> {
+        if !new_handshake.modifiers.list.contains(&HandshakeModifier::Fallback) {
+            return Err(NoiseError::InputError("into_fallback requires a pattern with the fallback modifier"));
+        }
+
+        let HandshakeState {
+            rng, symmetricstate, cipherstates, s, e, fixed_ephemeral, elligator,
+            rs, re, initiator, psks, prologue, ..
+        } = self;
+
+        // Whoever aborted the old handshake and is restarting it takes the opposite
+        // role in the new one; `HandshakeState::new` sets `my_turn` from `initiator`,
+        // so the fallback initiator writes first.
+        let initiator = !initiator;
+
+        let (cipherstate, hasher) = symmetricstate.checkpoint();
+
+        HandshakeState::new(
+            rng, cipherstate, hasher, s, e, fixed_ephemeral, elligator, rs, re, initiator,
+            new_handshake, psks, &prologue, cipherstates,
+        )
+    }
+
     fn dh_len(&self) -> usize {
         self.s.pub_len()
     }
@@ -135,12 +199,15 @@ impl HandshakeState {
         } else {
             let dh_len = self.dh_len();
             let mut dh_out = [0u8; MAXDHLEN];
+            // `rs`/`re` are fixed `MAXDHLEN`-sized buffers regardless of the DH in use;
+            // slice down to this DH's actual public key length before handing them to
+            // `Dh::dh`, which expects exactly that many bytes.
             match (local_s, remote_s) {
-                (true,  true ) => self.s.dh(&*self.rs, &mut dh_out),
-                (true,  false) => self.s.dh(&*self.re, &mut dh_out),
-                (false, true ) => self.e.dh(&*self.rs, &mut dh_out),
-                (false, false) => self.e.dh(&*self.re, &mut dh_out),
-            }
+                (true,  true ) => self.s.dh(&self.rs[..dh_len], &mut dh_out),
+                (true,  false) => self.s.dh(&self.re[..dh_len], &mut dh_out),
+                (false, true ) => self.e.dh(&self.rs[..dh_len], &mut dh_out),
+                (false, false) => self.e.dh(&self.re[..dh_len], &mut dh_out),
+            }.map_err(|_| NoiseError::StateError("dh operation failed"))?;
             self.symmetricstate.mix_key(&dh_out[..dh_len]);
             Ok(())
         }
@@ -247,11 +314,12 @@ impl HandshakeState {
             for token in tokens.iter() {
                 match *token {
                     Token::E => {
-                        self.re[..dh_len].copy_from_slice(&ptr[..dh_len]);
+                        let wire_bytes = &ptr[..dh_len];
+                        self.re[..dh_len].copy_from_slice(wire_bytes);
                         ptr = &ptr[dh_len..];
-                        self.symmetricstate.mix_hash(&self.re[..dh_len]);
+                        self.symmetricstate.mix_hash(wire_bytes);
                         if self.handshake.is_psk() {
-                            self.symmetricstate.mix_key(&self.re[..dh_len]);
+                            self.symmetricstate.mix_key(wire_bytes);
                         }
                         self.re.enable();
                     },
@@ -311,4 +379,110 @@ impl HandshakeState {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blake2::{Blake2s256, Digest};
+
+    struct TestRng(u8);
+
+    impl Random for TestRng {
+        fn fill_bytes(&mut self, out: &mut [u8]) {
+            for byte in out.iter_mut() {
+                self.0 = self.0.wrapping_add(1).wrapping_mul(37).wrapping_add(11);
+                *byte = self.0;
+            }
+        }
+    }
+
+    struct TestHash(Blake2s256);
+
+    impl TestHash {
+        fn new() -> Self {
+            TestHash(Blake2s256::default())
+        }
+    }
+
+    impl Hash for TestHash {
+        fn name(&self) -> &'static str {
+            "BLAKE2s"
+        }
+
+        fn block_len(&self) -> usize {
+            64
+        }
+
+        fn hash_len(&self) -> usize {
+            32
+        }
+
+        fn reset(&mut self) {
+            self.0 = Blake2s256::default();
+        }
+
+        fn input(&mut self, data: &[u8]) {
+            Digest::update(&mut self.0, data);
+        }
+
+        fn result(&mut self, out: &mut [u8]) {
+            let digest = self.0.clone().finalize();
+            out[..32].copy_from_slice(&digest);
+        }
+    }
+
+    fn dh25519(rng: &mut TestRng) -> Dh25519 {
+        let mut dh = Dh25519::new();
+        dh.generate(rng);
+        dh
+    }
+
+    // Noise Pipes: an IK responder whose view of the initiator's static key is stale
+    // (e.g. the initiator rotated keys) fails to decrypt the initiator's `s` token, but
+    // by then already holds the initiator's ephemeral in `re`. Driving that failure into
+    // `into_fallback` used to panic -- `HandshakeState::new`'s premessage loop, under the
+    // new (post-flip) `initiator == true`, asserted `e.is_on()` instead of checking `re`.
+    #[test]
+    fn ik_responder_falls_back_after_a_failed_read() {
+        let mut rng = TestRng(1);
+
+        let alice_s = dh25519(&mut rng);
+        let bob_stale_s = dh25519(&mut rng); // what Alice thinks Bob's static key is
+        let bob_s = dh25519(&mut rng); // Bob's actual (rotated) static key
+
+        let mut alice_rs = [0u8; MAXDHLEN];
+        alice_rs[..32].copy_from_slice(bob_stale_s.pubkey());
+
+        let mut alice = HandshakeState::new(
+            Box::new(TestRng(2)), CipherState::new(), Box::new(TestHash::new()),
+            Toggle::new(Box::new(alice_s) as Box<Dh>, true),
+            Toggle::new(Box::new(Dh25519::new()) as Box<Dh>, false),
+            false, false,
+            Toggle::new(alice_rs, true), Toggle::new([0u8; MAXDHLEN], false),
+            true, HandshakeChoice::new(HandshakePattern::IK), [None; 10], &[],
+            (CipherState::new(), CipherState::new()),
+        ).unwrap();
+
+        let mut bob = HandshakeState::new(
+            Box::new(TestRng(3)), CipherState::new(), Box::new(TestHash::new()),
+            Toggle::new(Box::new(bob_s) as Box<Dh>, true),
+            Toggle::new(Box::new(Dh25519::new()) as Box<Dh>, false),
+            false, false,
+            Toggle::new([0u8; MAXDHLEN], false), Toggle::new([0u8; MAXDHLEN], false),
+            false, HandshakeChoice::new(HandshakePattern::IK), [None; 10], &[],
+            (CipherState::new(), CipherState::new()),
+        ).unwrap();
+
+        let mut message = [0u8; MAXMSGLEN];
+        let len = alice.write_handshake_message(&[], &mut message).unwrap();
+
+        let mut payload = [0u8; MAXMSGLEN];
+        let err = bob.read_handshake_message(&message[..len], &mut payload).unwrap_err();
+        assert!(matches!(err, NoiseError::DecryptError));
+
+        let fallback = bob.into_fallback(
+            HandshakeChoice::with_modifiers(HandshakePattern::XXfallback, vec![HandshakeModifier::Fallback])
+        ).expect("into_fallback should succeed instead of panicking");
+        assert!(fallback.is_initiator());
+    }
+}
 
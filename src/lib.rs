@@ -0,0 +1,49 @@
+//! A pure-Rust implementation of the [Noise Protocol Framework](http://noiseprotocol.org/).
+
+extern crate arrayvec;
+extern crate blake2;
+extern crate chacha20poly1305;
+extern crate curve25519_dalek;
+
+use std::fmt;
+
+mod cipherstate;
+mod constants;
+mod params;
+mod symmetricstate;
+mod types;
+mod utils;
+
+pub mod error;
+pub mod handshakestate;
+pub mod transportstate;
+
+#[cfg(feature = "cookies")]
+pub mod cookie;
+#[cfg(feature = "cookies")]
+pub mod ratelimit;
+
+pub use types::Random;
+
+/// Errors produced by [`handshakestate::HandshakeState`].
+#[allow(missing_docs)]
+#[derive(Debug)]
+pub enum NoiseError {
+    StateError(&'static str),
+    InputError(&'static str),
+    PrereqError(String),
+    DecryptError,
+}
+
+impl fmt::Display for NoiseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NoiseError::StateError(s) => write!(f, "state error: {}", s),
+            NoiseError::InputError(s) => write!(f, "input error: {}", s),
+            NoiseError::PrereqError(s) => write!(f, "prerequisite error: {}", s),
+            NoiseError::DecryptError => write!(f, "decrypt error"),
+        }
+    }
+}
+
+impl std::error::Error for NoiseError {}
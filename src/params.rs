@@ -0,0 +1,141 @@
+//! Handshake pattern tokens, modifiers, and the small catalog of patterns this crate
+//! understands how to build a `HandshakeTokens` for.
+
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+
+/// A single token in a Noise handshake message pattern.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Token {
+    E,
+    S,
+    Psk(u8),
+    Dhee,
+    Dhes,
+    Dhse,
+    Dhss,
+}
+
+/// The base handshake patterns this crate knows how to build tokens for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HandshakePattern {
+    NN,
+    XX,
+    IK,
+    XXfallback,
+}
+
+impl HandshakePattern {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            HandshakePattern::NN => "NN",
+            HandshakePattern::XX => "XX",
+            HandshakePattern::IK => "IK",
+            HandshakePattern::XXfallback => "XXfallback",
+        }
+    }
+
+    fn premsg_pattern_i(&self) -> &'static [Token] {
+        match *self {
+            HandshakePattern::XXfallback => &[Token::E],
+            _ => &[],
+        }
+    }
+
+    fn premsg_pattern_r(&self) -> &'static [Token] {
+        match *self {
+            HandshakePattern::IK => &[Token::S],
+            _ => &[],
+        }
+    }
+
+    fn message_patterns(&self) -> Vec<Vec<Token>> {
+        match *self {
+            HandshakePattern::NN => vec![
+                vec![Token::E],
+                vec![Token::E, Token::Dhee],
+            ],
+            HandshakePattern::XX => vec![
+                vec![Token::E],
+                vec![Token::E, Token::Dhee, Token::S, Token::Dhes],
+                vec![Token::S, Token::Dhse],
+            ],
+            HandshakePattern::IK => vec![
+                vec![Token::E, Token::Dhes, Token::S, Token::Dhss],
+                vec![Token::E, Token::Dhee, Token::Dhse],
+            ],
+            HandshakePattern::XXfallback => vec![
+                vec![Token::E, Token::Dhee, Token::S, Token::Dhes],
+                vec![Token::S, Token::Dhse],
+            ],
+        }
+    }
+}
+
+/// A modifier appended to a base pattern's name, e.g. `+fallback` or `+psk0`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HandshakeModifier {
+    Psk(u8),
+    Fallback,
+}
+
+/// The (possibly empty) list of modifiers applied to a base pattern.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HandshakeModifierList {
+    pub list: Vec<HandshakeModifier>,
+}
+
+impl HandshakeModifierList {
+    pub fn new(list: Vec<HandshakeModifier>) -> HandshakeModifierList {
+        HandshakeModifierList { list }
+    }
+}
+
+/// A base pattern plus its modifiers -- everything needed to build a handshake name
+/// and its token sequence.
+#[derive(Clone)]
+pub struct HandshakeChoice {
+    pub pattern: HandshakePattern,
+    pub modifiers: HandshakeModifierList,
+}
+
+impl HandshakeChoice {
+    pub fn new(pattern: HandshakePattern) -> HandshakeChoice {
+        HandshakeChoice { pattern, modifiers: HandshakeModifierList::default() }
+    }
+
+    pub fn with_modifiers(pattern: HandshakePattern, modifiers: Vec<HandshakeModifier>) -> HandshakeChoice {
+        HandshakeChoice { pattern, modifiers: HandshakeModifierList::new(modifiers) }
+    }
+
+    pub fn is_psk(&self) -> bool {
+        self.modifiers.list.iter().any(|m| matches!(m, HandshakeModifier::Psk(_)))
+    }
+
+    pub fn is_fallback(&self) -> bool {
+        self.modifiers.list.contains(&HandshakeModifier::Fallback)
+    }
+}
+
+/// The pre-message and per-message token sequences derived from a `HandshakeChoice`.
+pub struct HandshakeTokens {
+    pub premsg_pattern_i: Vec<Token>,
+    pub premsg_pattern_r: Vec<Token>,
+    pub msg_patterns: Vec<Vec<Token>>,
+}
+
+/// `HandshakeState`'s queue of remaining message patterns; tokens are consumed off the
+/// front as each message is written/read.
+pub type MessagePatterns = VecDeque<Vec<Token>>;
+
+impl TryFrom<HandshakeChoice> for HandshakeTokens {
+    type Error = &'static str;
+
+    fn try_from(handshake: HandshakeChoice) -> Result<HandshakeTokens, Self::Error> {
+        Ok(HandshakeTokens {
+            premsg_pattern_i: handshake.pattern.premsg_pattern_i().to_vec(),
+            premsg_pattern_r: handshake.pattern.premsg_pattern_r().to_vec(),
+            msg_patterns: handshake.pattern.message_patterns(),
+        })
+    }
+}
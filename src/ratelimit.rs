@@ -0,0 +1,233 @@
+//! Per-source-IP rate limiting and TAI64N handshake replay rejection.
+//!
+//! Builds on [`cookie::CookieState`](crate::cookie::CookieState): once `mac1` has
+//! weeded out obviously spoofed traffic, `RateLimiter` gates the (comparatively
+//! expensive) initiation message itself, and `Tai64N` lets a responder refuse to
+//! process the same initiation payload twice.
+
+use error::{Error, StateProblem};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+/// Size in bytes of an encoded TAI64N timestamp: 8 bytes of seconds, 4 of nanoseconds.
+pub const TAI64N_LEN: usize = 12;
+
+const DEFAULT_HANDSHAKES_PER_SEC: u32 = 50;
+const DEFAULT_BURST: u32 = 5;
+const IDLE_ENTRY_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// Minimum gap between full `HashMap` sweeps of idle entries. Bounds the GC itself to
+/// an amortized cost, since a per-call sweep would turn an O(n) scan into the hot path
+/// a spoofed-source flood is specifically trying to overload.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(30);
+
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+/// Gates handshake initiations by source address with a token bucket per address,
+/// refilling at a fixed rate with a small burst allowance, and garbage-collecting
+/// buckets that have gone idle.
+///
+/// Intended to sit in front of [`HandshakeState::read_handshake_message`] for the
+/// first (initiation) message only: a caller checks [`RateLimiter::check`] and drops
+/// the packet without doing any further processing if it returns
+/// [`Error::RateLimited`](crate::error::Error::RateLimited).
+///
+/// [`HandshakeState::read_handshake_message`]: crate::handshakestate::HandshakeState::read_handshake_message
+pub struct RateLimiter {
+    rate_per_sec: f64,
+    burst: f64,
+    buckets: HashMap<IpAddr, TokenBucket>,
+    last_sweep: Instant,
+}
+
+impl RateLimiter {
+    /// Creates a rate limiter refilling `handshakes_per_sec` tokens/sec per source
+    /// address, with a burst allowance of `burst` handshakes.
+    pub fn new(handshakes_per_sec: u32, burst: u32) -> RateLimiter {
+        RateLimiter {
+            rate_per_sec: f64::from(handshakes_per_sec),
+            burst: f64::from(burst),
+            buckets: HashMap::new(),
+            last_sweep: Instant::now(),
+        }
+    }
+
+    /// Checks and consumes one token for `source_ip`, returning
+    /// `Err(Error::RateLimited)` if none are available. Also garbage-collects buckets
+    /// that haven't been touched in a while so memory doesn't grow unbounded under a
+    /// spoofed-source flood -- the sweep itself only runs at most once per
+    /// `SWEEP_INTERVAL`, so it can't become an O(n) cost on every single call.
+    pub fn check(&mut self, source_ip: IpAddr) -> Result<(), Error> {
+        let now = Instant::now();
+        if now.duration_since(self.last_sweep) >= SWEEP_INTERVAL {
+            self.buckets.retain(|_, bucket| now.duration_since(bucket.last_refill) < IDLE_ENTRY_TIMEOUT);
+            self.last_sweep = now;
+        }
+
+        let rate_per_sec = self.rate_per_sec;
+        let burst = self.burst;
+        let bucket = self.buckets.entry(source_ip).or_insert_with(|| TokenBucket { tokens: burst, last_refill: now });
+
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate_per_sec).min(burst);
+        bucket.last_refill = now;
+
+        if bucket.tokens < 1.0 {
+            return Err(Error::RateLimited);
+        }
+        bucket.tokens -= 1.0;
+        Ok(())
+    }
+}
+
+impl Default for RateLimiter {
+    fn default() -> RateLimiter {
+        RateLimiter::new(DEFAULT_HANDSHAKES_PER_SEC, DEFAULT_BURST)
+    }
+}
+
+/// The offset a TAI64 label adds to a Unix timestamp, per the TAI64 spec, so labels
+/// are representable as unsigned 64-bit integers without a sign bit.
+const TAI64_EPOCH_OFFSET: u64 = 1 << 62;
+
+/// A TAI64N timestamp, used as a handshake payload so a responder can reject replayed
+/// initiations: the greatest timestamp seen per remote static key is tracked, and any
+/// initiation whose timestamp isn't strictly greater is rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Tai64N {
+    /// The TAI64 label: Unix seconds plus `TAI64_EPOCH_OFFSET`.
+    seconds: u64,
+    nanos: u32,
+}
+
+impl Tai64N {
+    /// Builds a timestamp from a Unix epoch `(seconds, nanos)` pair, converting to the
+    /// TAI64 label (`unix_seconds + 2^62`) used on the wire.
+    pub fn from_unix(seconds: u64, nanos: u32) -> Tai64N {
+        Tai64N { seconds: seconds.wrapping_add(TAI64_EPOCH_OFFSET), nanos }
+    }
+
+    /// Encodes this timestamp as the 12-byte wire format: big-endian seconds, then
+    /// big-endian nanoseconds.
+    pub fn to_bytes(&self) -> [u8; TAI64N_LEN] {
+        let mut out = [0u8; TAI64N_LEN];
+        out[..8].copy_from_slice(&self.seconds.to_be_bytes());
+        out[8..].copy_from_slice(&self.nanos.to_be_bytes());
+        out
+    }
+
+    /// Decodes a 12-byte TAI64N timestamp from a handshake payload.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Tai64N, Error> {
+        if bytes.len() != TAI64N_LEN {
+            return Err(Error::Input);
+        }
+        let mut seconds_bytes = [0u8; 8];
+        seconds_bytes.copy_from_slice(&bytes[..8]);
+        let mut nanos_bytes = [0u8; 4];
+        nanos_bytes.copy_from_slice(&bytes[8..]);
+        Ok(Tai64N { seconds: u64::from_be_bytes(seconds_bytes), nanos: u32::from_be_bytes(nanos_bytes) })
+    }
+}
+
+struct ReplayEntry {
+    timestamp: Tai64N,
+    last_seen: Instant,
+}
+
+/// Tracks the greatest [`Tai64N`] timestamp seen per remote static key, rejecting any
+/// initiation whose timestamp is not strictly greater than the last one accepted.
+///
+/// Entries for peers that haven't been seen in a while are garbage-collected (like
+/// [`RateLimiter`]'s buckets), so this can't grow without bound under a flood of
+/// distinct spoofed static keys.
+pub struct ReplayFilter {
+    last_seen: HashMap<Vec<u8>, ReplayEntry>,
+    last_sweep: Instant,
+}
+
+impl ReplayFilter {
+    /// Creates an empty replay filter.
+    pub fn new() -> ReplayFilter {
+        ReplayFilter { last_seen: HashMap::new(), last_sweep: Instant::now() }
+    }
+
+    /// Validates and records `timestamp` for `remote_static_pubkey`, rejecting it with
+    /// `Error::State(StateProblem::ReplayedHandshake)` if it isn't strictly newer than
+    /// the last timestamp accepted for that peer.
+    pub fn check(&mut self, remote_static_pubkey: &[u8], timestamp: Tai64N) -> Result<(), Error> {
+        let now = Instant::now();
+        if now.duration_since(self.last_sweep) >= SWEEP_INTERVAL {
+            self.last_seen.retain(|_, entry| now.duration_since(entry.last_seen) < IDLE_ENTRY_TIMEOUT);
+            self.last_sweep = now;
+        }
+
+        if let Some(entry) = self.last_seen.get(remote_static_pubkey) {
+            if timestamp <= entry.timestamp {
+                return Err(Error::State(StateProblem::ReplayedHandshake));
+            }
+        }
+        self.last_seen.insert(remote_static_pubkey.to_vec(), ReplayEntry { timestamp, last_seen: now });
+        Ok(())
+    }
+}
+
+impl Default for ReplayFilter {
+    fn default() -> ReplayFilter {
+        ReplayFilter::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_limiter_allows_burst_then_denies() {
+        let mut limiter = RateLimiter::new(1, 3);
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+
+        for _ in 0..3 {
+            limiter.check(ip).expect("burst allowance should be available immediately");
+        }
+        assert!(matches!(limiter.check(ip), Err(Error::RateLimited)), "burst allowance should be exhausted");
+    }
+
+    #[test]
+    fn rate_limiter_tracks_sources_independently() {
+        let mut limiter = RateLimiter::new(1, 1);
+        let a: IpAddr = "192.0.2.1".parse().unwrap();
+        let b: IpAddr = "192.0.2.2".parse().unwrap();
+
+        limiter.check(a).unwrap();
+        assert!(matches!(limiter.check(a), Err(Error::RateLimited)));
+        limiter.check(b).expect("a different source address must have its own bucket");
+    }
+
+    #[test]
+    fn tai64n_round_trips_through_bytes() {
+        let ts = Tai64N::from_unix(1_700_000_000, 123_456);
+        let decoded = Tai64N::from_bytes(&ts.to_bytes()).unwrap();
+        assert_eq!(ts, decoded);
+        assert!(ts.seconds > TAI64_EPOCH_OFFSET, "encoded seconds must carry the TAI64 epoch offset");
+    }
+
+    #[test]
+    fn replay_filter_rejects_non_increasing_timestamps() {
+        let mut filter = ReplayFilter::new();
+        let pubkey = b"some remote static key".as_ref();
+
+        let first = Tai64N::from_unix(1000, 0);
+        let second = Tai64N::from_unix(1001, 0);
+
+        filter.check(pubkey, first).expect("first timestamp seen for a peer is always accepted");
+        assert!(
+            matches!(filter.check(pubkey, first), Err(Error::State(StateProblem::ReplayedHandshake))),
+            "a repeated timestamp must be rejected as a replay"
+        );
+        filter.check(pubkey, second).expect("a strictly greater timestamp must be accepted");
+    }
+}
@@ -0,0 +1,132 @@
+//! The `SymmetricState` object: tracks the running handshake hash and chaining key and
+//! derives the `CipherState`(s) `HandshakeState` uses to encrypt/decrypt messages.
+//!
+//! See: http://noiseprotocol.org/noise.html#the-symmetricstate-object
+
+use cipherstate::CipherState;
+use constants::{CIPHERKEYLEN, MAXHASHLEN};
+use types::Hash;
+use utils::copy_memory;
+
+pub struct SymmetricState {
+    cipherstate: CipherState,
+    hasher: Box<Hash>,
+    h: [u8; MAXHASHLEN],
+    ck: [u8; MAXHASHLEN],
+    has_key: bool,
+}
+
+impl SymmetricState {
+    pub fn new(cipherstate: CipherState, hasher: Box<Hash>) -> SymmetricState {
+        SymmetricState {
+            cipherstate,
+            hasher,
+            h: [0u8; MAXHASHLEN],
+            ck: [0u8; MAXHASHLEN],
+            has_key: false,
+        }
+    }
+
+    pub fn initialize(&mut self, handshake_name: &str) {
+        let hash_len = self.hasher.hash_len();
+        self.h = [0u8; MAXHASHLEN];
+        if handshake_name.len() <= hash_len {
+            self.h[..handshake_name.len()].copy_from_slice(handshake_name.as_bytes());
+        } else {
+            self.hasher.reset();
+            self.hasher.input(handshake_name.as_bytes());
+            self.hasher.result(&mut self.h[..hash_len]);
+        }
+        self.ck[..hash_len].copy_from_slice(&self.h[..hash_len]);
+        self.has_key = false;
+    }
+
+    pub fn mix_hash(&mut self, data: &[u8]) {
+        let hash_len = self.hasher.hash_len();
+        self.hasher.reset();
+        self.hasher.input(&self.h[..hash_len]);
+        self.hasher.input(data);
+        let mut result = [0u8; MAXHASHLEN];
+        self.hasher.result(&mut result[..hash_len]);
+        self.h[..hash_len].copy_from_slice(&result[..hash_len]);
+    }
+
+    pub fn mix_key(&mut self, data: &[u8]) {
+        let hash_len = self.hasher.hash_len();
+        let mut new_ck = [0u8; MAXHASHLEN];
+        let mut temp_k = [0u8; MAXHASHLEN];
+        self.hasher.hkdf2(&self.ck[..hash_len], data, &mut new_ck[..hash_len], &mut temp_k[..hash_len]);
+        self.ck = new_ck;
+
+        let mut key = [0u8; CIPHERKEYLEN];
+        key.copy_from_slice(&temp_k[..CIPHERKEYLEN]);
+        self.cipherstate.set_key(key);
+        self.has_key = true;
+    }
+
+    pub fn mix_key_and_hash(&mut self, data: &[u8]) {
+        let hash_len = self.hasher.hash_len();
+        let mut new_ck = [0u8; MAXHASHLEN];
+        let mut temp_h = [0u8; MAXHASHLEN];
+        let mut temp_k = [0u8; MAXHASHLEN];
+        self.hasher.hkdf3(&self.ck[..hash_len], data, &mut new_ck[..hash_len], &mut temp_h[..hash_len], &mut temp_k[..hash_len]);
+        self.ck = new_ck;
+        self.mix_hash(&temp_h[..hash_len]);
+
+        let mut key = [0u8; CIPHERKEYLEN];
+        key.copy_from_slice(&temp_k[..CIPHERKEYLEN]);
+        self.cipherstate.set_key(key);
+        self.has_key = true;
+    }
+
+    pub fn has_key(&self) -> bool {
+        self.has_key
+    }
+
+    pub fn encrypt_and_mix_hash(&mut self, plaintext: &[u8], out: &mut [u8]) -> usize {
+        let len = if self.has_key {
+            let h = self.h;
+            let hash_len = self.hasher.hash_len();
+            self.cipherstate.encrypt(self.cipherstate.nonce(), &h[..hash_len], plaintext, out)
+        } else {
+            copy_memory(plaintext, out)
+        };
+        self.mix_hash(&out[..len]);
+        len
+    }
+
+    pub fn decrypt_and_mix_hash(&mut self, data: &[u8], out: &mut [u8]) -> Result<usize, ()> {
+        let len = if self.has_key {
+            let h = self.h;
+            let hash_len = self.hasher.hash_len();
+            self.cipherstate.decrypt(self.cipherstate.nonce(), &h[..hash_len], data, out)?
+        } else {
+            copy_memory(data, out)
+        };
+        self.mix_hash(data);
+        Ok(len)
+    }
+
+    pub fn split(&mut self, c1: &mut CipherState, c2: &mut CipherState) {
+        let hash_len = self.hasher.hash_len();
+        let mut temp_k1 = [0u8; MAXHASHLEN];
+        let mut temp_k2 = [0u8; MAXHASHLEN];
+        self.hasher.hkdf2(&self.ck[..hash_len], &[], &mut temp_k1[..hash_len], &mut temp_k2[..hash_len]);
+
+        let mut k1 = [0u8; CIPHERKEYLEN];
+        k1.copy_from_slice(&temp_k1[..CIPHERKEYLEN]);
+        let mut k2 = [0u8; CIPHERKEYLEN];
+        k2.copy_from_slice(&temp_k2[..CIPHERKEYLEN]);
+        c1.set_key(k1);
+        c2.set_key(k2);
+    }
+
+    /// Pulls a fresh `CipherState` and this state's hasher back out of a live
+    /// `SymmetricState`, for [`HandshakeState::into_fallback`](crate::handshakestate::HandshakeState::into_fallback):
+    /// the fallback handshake re-initializes its own transcript hash from scratch (per
+    /// Noise Pipes), so only the hasher implementation is worth keeping -- the cipher
+    /// and chaining keys are discarded along with `self`.
+    pub fn checkpoint(self) -> (CipherState, Box<Hash>) {
+        (CipherState::default(), self.hasher)
+    }
+}
@@ -0,0 +1,145 @@
+//! The transport phase of a Noise session.
+//!
+//! A `TransportState` wraps the `CipherStates` produced by
+//! [`HandshakeState::finish`](crate::handshakestate::HandshakeState::finish) and adds the
+//! operations long-lived sessions need that the handshake phase has no use for: rotating
+//! a traffic key without resetting its nonce counter, and reading/setting the nonces
+//! directly so a caller can carry explicit per-message nonces over a lossy or
+//! out-of-order datagram channel (`StateProblem::StatelessTransportMode`) instead of
+//! relying on an internal auto-incrementing counter.
+
+use cipherstate::CipherState;
+use error::{Error, StateProblem};
+use handshakestate::HandshakeState;
+
+const CIPHER_KEY_LEN: usize = 32;
+
+/// A transport session: the pair of `CipherState`s from a finished handshake, plus
+/// which direction (`cipherstates.0`, initiator-to-responder, or `cipherstates.1`,
+/// responder-to-initiator) this end of the session sends on.
+pub struct TransportState {
+    initiator_to_responder: CipherState,
+    responder_to_initiator: CipherState,
+    initiator: bool,
+}
+
+impl TransportState {
+    /// Completes `handshake` into a transport session ready to encrypt/decrypt
+    /// application data. Fails with `StateProblem::HandshakeNotFinished` if the
+    /// handshake hasn't finished (i.e. `split` hasn't happened yet).
+    pub fn new(handshake: HandshakeState) -> Result<TransportState, Error> {
+        let initiator = handshake.is_initiator();
+        let (cipherstates, _) = handshake.finish()
+            .map_err(|_| Error::State(StateProblem::HandshakeNotFinished))?;
+        Ok(TransportState {
+            initiator_to_responder: cipherstates.0,
+            responder_to_initiator: cipherstates.1,
+            initiator,
+        })
+    }
+
+    fn sending(&mut self) -> &mut CipherState {
+        if self.initiator { &mut self.initiator_to_responder } else { &mut self.responder_to_initiator }
+    }
+
+    fn receiving(&mut self) -> &mut CipherState {
+        if self.initiator { &mut self.responder_to_initiator } else { &mut self.initiator_to_responder }
+    }
+
+    /// Rekeys the initiator-to-responder cipherstate per the Noise `REKEY(k)` rule:
+    /// `k' = ENCRYPT(k, maxnonce, zerolen, zeros)[..32]`. The nonce counter is left
+    /// untouched; only the key changes. Both peers call this independently (the
+    /// derivation is deterministic from the current key) to stay in sync.
+    pub fn rekey_initiator(&mut self) -> Result<(), Error> {
+        rekey(&mut self.initiator_to_responder)
+    }
+
+    /// Rekeys the responder-to-initiator cipherstate per the same `REKEY(k)` rule.
+    pub fn rekey_responder(&mut self) -> Result<(), Error> {
+        rekey(&mut self.responder_to_initiator)
+    }
+
+    /// Sets this session's own sending cipherstate directly to `key`, for callers
+    /// managing their own key schedule out-of-band instead of deriving via `REKEY(k)`.
+    /// Like `rekey_initiator`/`rekey_responder`, this leaves the nonce counter alone.
+    pub fn rekey_manual(&mut self, key: &[u8; CIPHER_KEY_LEN]) {
+        let sending = self.sending();
+        let nonce = sending.nonce();
+        sending.set_key(*key);
+        sending.set_nonce(nonce);
+    }
+
+    /// The next nonce that will be used to encrypt an outgoing message.
+    pub fn sending_nonce(&mut self) -> u64 {
+        self.sending().nonce()
+    }
+
+    /// Overrides the nonce used for the next outgoing message, for explicit
+    /// (stateless-transport) nonce management.
+    pub fn set_sending_nonce(&mut self, nonce: u64) {
+        self.sending().set_nonce(nonce);
+    }
+
+    /// The next nonce expected on an incoming message.
+    pub fn receiving_nonce(&mut self) -> u64 {
+        self.receiving().nonce()
+    }
+
+    /// Overrides the nonce expected on the next incoming message, for explicit
+    /// (stateless-transport) nonce management.
+    pub fn set_receiving_nonce(&mut self, nonce: u64) {
+        self.receiving().set_nonce(nonce);
+    }
+
+    /// Whether this session is the handshake initiator (and so sends on
+    /// initiator-to-responder, receives on responder-to-initiator).
+    pub fn is_initiator(&self) -> bool {
+        self.initiator
+    }
+}
+
+fn rekey(cipherstate: &mut CipherState) -> Result<(), Error> {
+    // Snapshot the nonce before `encrypt` advances it (to `u64::MAX.wrapping_add(1) ==
+    // 0`) -- it must be taken before the REKEY(k) call below, not after, or the
+    // restore at the end of this function just reinstates the post-encrypt value
+    // instead of the counter's true pre-rekey position.
+    let nonce = cipherstate.nonce();
+
+    let zeros = [0u8; CIPHER_KEY_LEN];
+    let mut ciphertext = [0u8; CIPHER_KEY_LEN + 16];
+    cipherstate.encrypt(u64::max_value(), &[], &zeros, &mut ciphertext);
+
+    let mut new_key = [0u8; CIPHER_KEY_LEN];
+    new_key.copy_from_slice(&ciphertext[..CIPHER_KEY_LEN]);
+
+    // CipherState::set_key resets the nonce counter to 0 (correct for its other
+    // caller, initial key establishment) -- restore it here so REKEY(k) actually
+    // leaves the nonce counter untouched, as required.
+    cipherstate.set_key(new_key);
+    cipherstate.set_nonce(nonce);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rekey_rotates_key_without_resetting_nonce() {
+        let mut cipherstate = CipherState::default();
+        cipherstate.set_key([7u8; CIPHER_KEY_LEN]);
+        cipherstate.set_nonce(42);
+
+        let mut before_ciphertext = [0u8; 16];
+        let mut probe = CipherState::default();
+        probe.set_key([7u8; CIPHER_KEY_LEN]);
+        probe.encrypt(42, &[], &[], &mut before_ciphertext);
+
+        rekey(&mut cipherstate).unwrap();
+        assert_eq!(cipherstate.nonce(), 42, "rekey must leave the nonce counter untouched");
+
+        let mut after_ciphertext = [0u8; 16];
+        cipherstate.encrypt(42, &[], &[], &mut after_ciphertext);
+        assert_ne!(before_ciphertext, after_ciphertext, "rekey must actually rotate the key");
+    }
+}
@@ -0,0 +1,208 @@
+//! Core cryptographic trait objects (`Dh`, `Hash`, `Random`) and the `Toggle<T>`
+//! wrapper used throughout `HandshakeState` to track which key material is present.
+
+use std::ops::{Deref, DerefMut};
+
+/// A source of cryptographically secure random bytes.
+pub trait Random: Send + Sync {
+    fn fill_bytes(&mut self, out: &mut [u8]);
+}
+
+/// A Diffie-Hellman key-agreement function.
+pub trait Dh: Send + Sync {
+    /// The name this DH function is identified by in a Noise handshake name.
+    fn name(&self) -> &'static str;
+    /// Length in bytes of a public key.
+    fn pub_len(&self) -> usize;
+    /// Length in bytes of a private key.
+    fn priv_len(&self) -> usize;
+    /// Sets this DH's private key (and derives the matching public key).
+    fn set(&mut self, privkey: &[u8]);
+    /// Generates a new keypair from `rng`.
+    fn generate(&mut self, rng: &mut dyn Random);
+    /// This DH's current public key.
+    fn pubkey(&self) -> &[u8];
+    /// This DH's current private key.
+    fn privkey(&self) -> &[u8];
+    /// Performs a DH operation against `pubkey`, writing the shared secret to `out`.
+    fn dh(&self, pubkey: &[u8], out: &mut [u8]) -> Result<(), ()>;
+
+    /// Returns an Elligator2 representative of this DH's current public key, if the
+    /// key happens to have one (true for roughly half of all possible public keys).
+    /// Nothing in this crate calls it, and no implementation currently ships: no published `curve25519-dalek` release exposes the public Montgomery
+    /// Elligator2 API this would need to build on (only an internal, pub(crate)
+    /// hash-to-curve helper exists), so every `Dh` falls back to this default.
+    fn elligator2_representative(&self, rng: &mut dyn Random) -> Option<[u8; 32]> {
+        let _ = rng;
+        None
+    }
+}
+
+/// A cryptographic hash function, with HMAC/HKDF built on top of `input`/`result` per
+/// the Noise spec's `HKDF(chaining_key, input_key_material, num_outputs)`.
+pub trait Hash: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn block_len(&self) -> usize;
+    fn hash_len(&self) -> usize;
+    fn reset(&mut self);
+    fn input(&mut self, data: &[u8]);
+    fn result(&mut self, out: &mut [u8]);
+
+    fn hmac(&mut self, key: &[u8], data: &[u8], out: &mut [u8]) {
+        let block_len = self.block_len();
+        assert!(key.len() <= block_len);
+
+        let mut ipad = vec![0x36u8; block_len];
+        let mut opad = vec![0x5cu8; block_len];
+        for (count, byte) in key.iter().enumerate() {
+            ipad[count] ^= byte;
+            opad[count] ^= byte;
+        }
+
+        let hash_len = self.hash_len();
+        let mut inner = vec![0u8; hash_len];
+        self.reset();
+        self.input(&ipad);
+        self.input(data);
+        self.result(&mut inner);
+
+        self.reset();
+        self.input(&opad);
+        self.input(&inner);
+        self.result(out);
+    }
+
+    fn hkdf2(&mut self, chaining_key: &[u8], input_key_material: &[u8], out1: &mut [u8], out2: &mut [u8]) {
+        let hash_len = self.hash_len();
+        let mut temp_key = vec![0u8; hash_len];
+        self.hmac(chaining_key, input_key_material, &mut temp_key);
+        self.hmac(&temp_key, &[1u8], out1);
+
+        let mut input2 = Vec::with_capacity(hash_len + 1);
+        input2.extend_from_slice(&out1[..hash_len]);
+        input2.push(2u8);
+        self.hmac(&temp_key, &input2, out2);
+    }
+
+    fn hkdf3(&mut self, chaining_key: &[u8], input_key_material: &[u8], out1: &mut [u8], out2: &mut [u8], out3: &mut [u8]) {
+        let hash_len = self.hash_len();
+        let mut temp_key = vec![0u8; hash_len];
+        self.hmac(chaining_key, input_key_material, &mut temp_key);
+        self.hmac(&temp_key, &[1u8], out1);
+
+        let mut input2 = Vec::with_capacity(hash_len + 1);
+        input2.extend_from_slice(&out1[..hash_len]);
+        input2.push(2u8);
+        self.hmac(&temp_key, &input2, out2);
+
+        let mut input3 = Vec::with_capacity(hash_len + 1);
+        input3.extend_from_slice(&out2[..hash_len]);
+        input3.push(3u8);
+        self.hmac(&temp_key, &input3, out3);
+    }
+}
+
+/// Wraps a value with an on/off flag tracking whether that key material is present
+/// yet, without having to thread `Option<T>` through every call site -- derefs
+/// straight to `T` so callers can keep indexing/method-calling through it.
+pub struct Toggle<T> {
+    value: T,
+    on: bool,
+}
+
+impl<T> Toggle<T> {
+    pub fn new(value: T, on: bool) -> Self {
+        Toggle { value, on }
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.on
+    }
+
+    pub fn enable(&mut self) {
+        self.on = true;
+    }
+
+    pub fn disable(&mut self) {
+        self.on = false;
+    }
+}
+
+impl<T> Deref for Toggle<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> DerefMut for Toggle<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+/// X25519 Diffie-Hellman.
+pub struct Dh25519 {
+    privkey: [u8; 32],
+    pubkey: [u8; 32],
+}
+
+impl Dh25519 {
+    pub fn new() -> Dh25519 {
+        Dh25519 { privkey: [0u8; 32], pubkey: [0u8; 32] }
+    }
+
+    fn derive_pubkey(&mut self) {
+        use curve25519_dalek::montgomery::MontgomeryPoint;
+        use curve25519_dalek::scalar::clamp_integer;
+
+        let clamped = clamp_integer(self.privkey);
+        let point = MontgomeryPoint::mul_base_clamped(clamped);
+        self.pubkey.copy_from_slice(point.as_bytes());
+    }
+}
+
+impl Dh for Dh25519 {
+    fn name(&self) -> &'static str {
+        "25519"
+    }
+
+    fn pub_len(&self) -> usize {
+        32
+    }
+
+    fn priv_len(&self) -> usize {
+        32
+    }
+
+    fn set(&mut self, privkey: &[u8]) {
+        self.privkey.copy_from_slice(privkey);
+        self.derive_pubkey();
+    }
+
+    fn generate(&mut self, rng: &mut dyn Random) {
+        rng.fill_bytes(&mut self.privkey);
+        self.derive_pubkey();
+    }
+
+    fn pubkey(&self) -> &[u8] {
+        &self.pubkey
+    }
+
+    fn privkey(&self) -> &[u8] {
+        &self.privkey
+    }
+
+    fn dh(&self, pubkey: &[u8], out: &mut [u8]) -> Result<(), ()> {
+        use curve25519_dalek::montgomery::MontgomeryPoint;
+        use curve25519_dalek::scalar::clamp_integer;
+
+        let mut their_pubkey = [0u8; 32];
+        their_pubkey.copy_from_slice(pubkey);
+        let clamped = clamp_integer(self.privkey);
+        let shared = MontgomeryPoint(their_pubkey).mul_clamped(clamped);
+        out[..32].copy_from_slice(shared.as_bytes());
+        Ok(())
+    }
+}
@@ -0,0 +1,7 @@
+//! Small helpers shared across the handshake/transport machinery.
+
+/// Copies `data` into the front of `out`, returning the number of bytes copied.
+pub fn copy_memory(data: &[u8], out: &mut [u8]) -> usize {
+    out[..data.len()].copy_from_slice(data);
+    data.len()
+}